@@ -1,5 +1,6 @@
 use std::fs;
 use std::path::PathBuf;
+use std::process::ExitCode;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -55,7 +56,7 @@ struct Cli {
     /// NWS Proxy
     #[arg(short, long)]
     proxy: Option<Url>,
-    /// Currency unit to use for the wallet
+    /// Currency unit to use for the wallet (`balance` also accepts `all` to show every held unit)
     #[arg(short, long, default_value = "sat")]
     unit: String,
     /// Use Tor transport (only when built with --features tor). Defaults to 'on' when feature is enabled.
@@ -72,7 +73,7 @@ enum Commands {
     /// Decode a token
     DecodeToken(sub_commands::decode_token::DecodeTokenSubCommand),
     /// Balance
-    Balance,
+    Balance(sub_commands::balance::BalanceSubCommand),
     /// Pay bolt11 invoice
     Melt(sub_commands::melt::MeltSubCommand),
     /// Claim pending mint quotes that have been paid
@@ -112,7 +113,7 @@ enum Commands {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     let args: Cli = Cli::parse();
     let default_filter = args.log_level;
 
@@ -123,6 +124,17 @@ async fn main() -> Result<()> {
     // Parse input
     tracing_subscriber::fmt().with_env_filter(env_filter).init();
 
+    if args
+        .unit
+        .eq_ignore_ascii_case(sub_commands::balance::ALL_UNITS)
+        && !matches!(args.command, Commands::Balance(_))
+    {
+        bail!(
+            "`--unit {}` is only supported by the `balance` command",
+            sub_commands::balance::ALL_UNITS
+        );
+    }
+
     let work_dir = match &args.work_dir {
         Some(work_dir) => work_dir.clone(),
         None => {
@@ -228,11 +240,19 @@ async fn main() -> Result<()> {
         }
     };
 
-    match &args.command {
+    let result = match &args.command {
         Commands::DecodeToken(sub_command_args) => {
             sub_commands::decode_token::decode_token(sub_command_args)
         }
-        Commands::Balance => sub_commands::balance::balance(&multi_mint_wallet).await,
+        Commands::Balance(sub_command_args) => {
+            let has_balance =
+                sub_commands::balance::balance(&multi_mint_wallet, &localstore, sub_command_args)
+                    .await?;
+            return Ok(ExitCode::from(sub_commands::balance::exit_code(
+                sub_command_args,
+                has_balance,
+            )));
+        }
         Commands::Melt(sub_command_args) => {
             sub_commands::melt::pay(&multi_mint_wallet, sub_command_args).await
         }
@@ -299,5 +319,7 @@ async fn main() -> Result<()> {
             )
             .await
         }
-    }
+    };
+
+    result.map(|()| ExitCode::SUCCESS)
 }
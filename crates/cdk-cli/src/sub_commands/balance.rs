@@ -1,45 +1,975 @@
 use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use cdk::cdk_database::{self, WalletDatabase};
 use cdk::mint_url::MintUrl;
-use cdk::nuts::CurrencyUnit;
+use cdk::nuts::{CurrencyUnit, State};
+use cdk::util::unix_time;
 use cdk::wallet::MultiMintWallet;
 use cdk::Amount;
+use clap::Args;
+use serde::Serialize;
+use tempfile::NamedTempFile;
 
-pub async fn balance(multi_mint_wallet: &MultiMintWallet) -> Result<()> {
-    // Show individual mint balances
-    let mint_balances = mint_balances(multi_mint_wallet, multi_mint_wallet.unit()).await?;
+/// Value of the global `--unit` option that shows balances for every held unit
+pub const ALL_UNITS: &str = "all";
 
-    // Show total balance using the new unified interface
-    let total = multi_mint_wallet.total_balance().await?;
-    if !mint_balances.is_empty() {
-        println!();
-        println!(
-            "Total balance across all wallets: {} {}",
-            total,
-            multi_mint_wallet.unit()
-        );
+/// Exit code used by `--fail-if-empty` when no wallet holds a balance, kept
+/// distinct from the code 1 returned for errors
+const EMPTY_BALANCE_EXIT_CODE: u8 = 2;
+
+#[derive(Args)]
+pub struct BalanceSubCommand {
+    /// Write per-mint balances as CSV (mint_url,amount,unit) to the given file,
+    /// or to stdout if no path is given
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["json", "verbose"])]
+    csv: Option<Option<PathBuf>>,
+    /// Print balances as JSON
+    #[arg(long)]
+    json: bool,
+    /// Also show the proof count and smallest/largest proof denomination per mint
+    #[arg(short, long)]
+    verbose: bool,
+    /// Exit with code 2 when there is no balance in any wallet, instead of
+    /// always exiting with 0 (errors still exit with 1)
+    #[arg(long)]
+    fail_if_empty: bool,
+}
+
+/// Proof fragmentation details for a single mint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ProofStats {
+    /// Number of unspent proofs
+    pub proof_count: usize,
+    /// Smallest proof denomination
+    pub min_denom: Option<Amount>,
+    /// Largest proof denomination
+    pub max_denom: Option<Amount>,
+}
+
+impl ProofStats {
+    fn from_amounts<I>(amounts: I) -> Self
+    where
+        I: IntoIterator<Item = Amount>,
+    {
+        amounts.into_iter().fold(
+            Self {
+                proof_count: 0,
+                min_denom: None,
+                max_denom: None,
+            },
+            |stats, amount| Self {
+                proof_count: stats.proof_count + 1,
+                min_denom: Some(stats.min_denom.map_or(amount, |min| min.min(amount))),
+                max_denom: Some(stats.max_denom.map_or(amount, |max| max.max(amount))),
+            },
+        )
     }
+}
 
-    Ok(())
+/// Non-zero per-mint balances held in a single unit
+struct UnitBalances {
+    unit: CurrencyUnit,
+    balances: Vec<(MintUrl, Amount)>,
+    proof_stats: Option<BTreeMap<MintUrl, ProofStats>>,
+}
+
+/// Balances for one or more units, fetched at a single point in time
+struct Balances {
+    units: Vec<UnitBalances>,
+    /// Unix timestamp at which the balances were fetched
+    last_updated: u64,
+}
+
+impl Balances {
+    fn has_balance(&self) -> bool {
+        self.units
+            .iter()
+            .any(|unit_balances| !unit_balances.balances.is_empty())
+    }
+}
+
+impl UnitBalances {
+    fn total(&self) -> Result<Amount> {
+        Ok(Amount::try_sum(
+            self.balances.iter().map(|(_, amount)| *amount),
+        )?)
+    }
+}
+
+#[derive(Serialize)]
+struct MintBalance {
+    mint_url: MintUrl,
+    amount: Amount,
+    unit: CurrencyUnit,
+    #[serde(flatten)]
+    proofs: Option<ProofStats>,
+}
+
+#[derive(Serialize)]
+struct UnitBalanceOutput {
+    balances: Vec<MintBalance>,
+    total: Amount,
+    unit: CurrencyUnit,
+}
+
+impl UnitBalanceOutput {
+    fn new(unit_balances: &UnitBalances) -> Result<Self> {
+        let balances = unit_balances
+            .balances
+            .iter()
+            .map(|(mint_url, amount)| MintBalance {
+                mint_url: mint_url.clone(),
+                amount: *amount,
+                unit: unit_balances.unit.clone(),
+                proofs: unit_balances
+                    .proof_stats
+                    .as_ref()
+                    .and_then(|stats| stats.get(mint_url).copied()),
+            })
+            .collect();
+
+        Ok(Self {
+            balances,
+            total: unit_balances.total()?,
+            unit: unit_balances.unit.clone(),
+        })
+    }
+}
+
+/// JSON output, with the same shape for a single unit and for `--unit all`
+#[derive(Serialize)]
+struct BalanceOutput {
+    units: Vec<UnitBalanceOutput>,
+    last_updated: u64,
+}
+
+impl BalanceOutput {
+    fn new(balances: &Balances) -> Result<Self> {
+        Ok(Self {
+            units: balances
+                .units
+                .iter()
+                .map(UnitBalanceOutput::new)
+                .collect::<Result<_>>()?,
+            last_updated: balances.last_updated,
+        })
+    }
+}
+
+/// Show balances, returning whether any wallet holds a balance
+pub async fn balance(
+    multi_mint_wallet: &MultiMintWallet,
+    localstore: &Arc<dyn WalletDatabase<Err = cdk_database::Error> + Send + Sync>,
+    sub_command_args: &BalanceSubCommand,
+) -> Result<bool> {
+    if is_all_units(multi_mint_wallet.unit()) {
+        all_units_balance(localstore, sub_command_args).await
+    } else {
+        unit_balance(multi_mint_wallet, sub_command_args).await
+    }
+}
+
+/// Process exit code for a successful `balance` run, noting on stderr when
+/// `--fail-if-empty` fails the run
+pub fn exit_code(sub_command_args: &BalanceSubCommand, has_balance: bool) -> u8 {
+    if sub_command_args.fail_if_empty && !has_balance {
+        eprintln!("No balance in any wallet");
+        EMPTY_BALANCE_EXIT_CODE
+    } else {
+        0
+    }
+}
+
+/// Show balances for the wallet unit, returning whether any mint holds a balance
+async fn unit_balance(
+    multi_mint_wallet: &MultiMintWallet,
+    sub_command_args: &BalanceSubCommand,
+) -> Result<bool> {
+    if let Some(csv_path) = &sub_command_args.csv {
+        return export_csv(multi_mint_wallet, csv_path.as_ref()).await;
+    }
+
+    let proof_stats = if sub_command_args.verbose {
+        Some(proof_stats(multi_mint_wallet).await?)
+    } else {
+        None
+    };
+    let balances = fetch_unit_balances(multi_mint_wallet, proof_stats).await?;
+
+    if sub_command_args.json {
+        write_json(&mut io::stdout().lock(), &balances)?;
+    } else {
+        write_unit_text(&mut io::stdout().lock(), &balances)?;
+    }
+
+    Ok(balances.has_balance())
 }
 
-pub async fn mint_balances(
+/// Fetch non-zero per-mint balances for the wallet unit, recording when they were read
+async fn fetch_unit_balances(
     multi_mint_wallet: &MultiMintWallet,
+    proof_stats: Option<BTreeMap<MintUrl, ProofStats>>,
+) -> Result<Balances> {
+    let balances = non_zero_balances(multi_mint_wallet).await?;
+
+    Ok(Balances {
+        units: vec![UnitBalances {
+            unit: multi_mint_wallet.unit().clone(),
+            balances,
+            proof_stats,
+        }],
+        last_updated: unix_time(),
+    })
+}
+
+fn write_json<W>(writer: &mut W, balances: &Balances) -> Result<()>
+where
+    W: Write,
+{
+    serde_json::to_writer_pretty(&mut *writer, &BalanceOutput::new(balances)?)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Render per-mint balances for the wallet unit, followed by the total
+fn write_unit_text<W>(writer: &mut W, balances: &Balances) -> Result<()>
+where
+    W: Write,
+{
+    for unit_balances in &balances.units {
+        write_mint_balances(
+            writer,
+            &unit_balances.balances,
+            &unit_balances.unit,
+            unit_balances.proof_stats.as_ref(),
+        )?;
+
+        if !unit_balances.balances.is_empty() {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "Total balance across all wallets: {} {}",
+                unit_balances.total()?,
+                unit_balances.unit
+            )?;
+        }
+    }
+    writeln!(
+        writer,
+        "Last updated: {}",
+        format_unix_time(balances.last_updated)
+    )?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_mint_balances<W>(
+    writer: &mut W,
+    balances: &[(MintUrl, Amount)],
     unit: &CurrencyUnit,
-) -> Result<Vec<(MintUrl, Amount)>> {
+    proof_stats: Option<&BTreeMap<MintUrl, ProofStats>>,
+) -> Result<()>
+where
+    W: Write,
+{
+    for (i, (mint_url, amount)) in balances.iter().enumerate() {
+        writeln!(writer, "{i}: {mint_url} {amount} {unit}")?;
+
+        if let Some(stats) = proof_stats.and_then(|stats| stats.get(mint_url)) {
+            if let (Some(min), Some(max)) = (stats.min_denom, stats.max_denom) {
+                let proofs = if stats.proof_count == 1 {
+                    "proof"
+                } else {
+                    "proofs"
+                };
+                writeln!(
+                    writer,
+                    "   {} {proofs}, smallest {min} {unit}, largest {max} {unit}",
+                    stats.proof_count
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Format a unix timestamp as a UTC date and time, e.g. `2025-01-31 14:05:00 UTC`
+fn format_unix_time(timestamp: u64) -> String {
+    let (days, secs) = (timestamp / 86_400, timestamp % 86_400);
+
+    // Convert days since the epoch to a Gregorian calendar date
+    // (http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+fn is_all_units(unit: &CurrencyUnit) -> bool {
+    matches!(unit, CurrencyUnit::Custom(unit) if unit.eq_ignore_ascii_case(ALL_UNITS))
+}
+
+/// Show balances for every unit that has unspent proofs in the wallet database,
+/// returning whether any unit holds a balance
+async fn all_units_balance(
+    localstore: &Arc<dyn WalletDatabase<Err = cdk_database::Error> + Send + Sync>,
+    sub_command_args: &BalanceSubCommand,
+) -> Result<bool> {
+    // Create the export before fetching balances so an unwritable path fails fast
+    let export = match &sub_command_args.csv {
+        Some(Some(path)) => Some(CsvExport::create(path)?),
+        _ => None,
+    };
+
+    let balances = all_unit_balances(localstore, sub_command_args.verbose).await?;
+
+    if sub_command_args.csv.is_some() {
+        match export {
+            Some(export) => export.finish(&balances.units)?,
+            None => write_csv(&mut io::stdout().lock(), &balances.units)?,
+        }
+    } else if sub_command_args.json {
+        write_json(&mut io::stdout().lock(), &balances)?;
+    } else {
+        write_all_units_text(&mut io::stdout().lock(), &balances)?;
+    }
+
+    Ok(balances.has_balance())
+}
+
+/// Render per-mint balances grouped by unit, followed by the total for each unit
+fn write_all_units_text<W>(writer: &mut W, balances: &Balances) -> Result<()>
+where
+    W: Write,
+{
+    for unit_balances in &balances.units {
+        writeln!(writer, "{}:", unit_balances.unit)?;
+        write_mint_balances(
+            writer,
+            &unit_balances.balances,
+            &unit_balances.unit,
+            unit_balances.proof_stats.as_ref(),
+        )?;
+        writeln!(writer)?;
+    }
+
+    if balances.units.is_empty() {
+        writeln!(writer, "No balance in any unit")?;
+    } else {
+        writeln!(writer, "Total balance per unit:")?;
+        for unit_balances in &balances.units {
+            writeln!(writer, "{} {}", unit_balances.total()?, unit_balances.unit)?;
+        }
+    }
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "Last updated: {}",
+        format_unix_time(balances.last_updated)
+    )?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Group unspent proofs by unit and mint
+async fn all_unit_balances(
+    localstore: &Arc<dyn WalletDatabase<Err = cdk_database::Error> + Send + Sync>,
+    with_proof_stats: bool,
+) -> Result<Balances> {
+    let proofs = localstore
+        .get_proofs(None, None, Some(vec![State::Unspent]), None)
+        .await?;
+    let last_updated = unix_time();
+
+    let mut grouped: BTreeMap<CurrencyUnit, BTreeMap<MintUrl, Vec<Amount>>> = BTreeMap::new();
+    for proof_info in proofs {
+        grouped
+            .entry(proof_info.unit)
+            .or_default()
+            .entry(proof_info.mint_url)
+            .or_default()
+            .push(proof_info.proof.amount);
+    }
+
+    let units = grouped
+        .into_iter()
+        .map(|(unit, mints)| {
+            let balances = mints
+                .iter()
+                .map(|(mint_url, amounts)| {
+                    Ok((mint_url.clone(), Amount::try_sum(amounts.iter().copied())?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let proof_stats = with_proof_stats.then(|| {
+                mints
+                    .into_iter()
+                    .map(|(mint_url, amounts)| (mint_url, ProofStats::from_amounts(amounts)))
+                    .collect()
+            });
+
+            Ok(UnitBalances {
+                unit,
+                balances,
+                proof_stats,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Balances {
+        units,
+        last_updated,
+    })
+}
+
+/// Get proof count and denomination range for each mint
+async fn proof_stats(multi_mint_wallet: &MultiMintWallet) -> Result<BTreeMap<MintUrl, ProofStats>> {
+    Ok(multi_mint_wallet
+        .list_proofs()
+        .await?
+        .into_iter()
+        .map(|(mint_url, proofs)| {
+            let stats = ProofStats::from_amounts(proofs.iter().map(|proof| proof.amount));
+            (mint_url, stats)
+        })
+        .collect())
+}
+
+async fn non_zero_balances(multi_mint_wallet: &MultiMintWallet) -> Result<Vec<(MintUrl, Amount)>> {
     let wallets: BTreeMap<MintUrl, Amount> = multi_mint_wallet.get_balances().await?;
 
-    let mut wallets_vec = Vec::with_capacity(wallets.len());
+    Ok(wallets
+        .into_iter()
+        .filter(|(_, amount)| amount > &Amount::ZERO)
+        .collect())
+}
+
+async fn export_csv(multi_mint_wallet: &MultiMintWallet, path: Option<&PathBuf>) -> Result<bool> {
+    // Create the export before fetching balances so an unwritable path fails fast
+    let export = path.map(|path| CsvExport::create(path)).transpose()?;
 
-    for (i, (mint_url, amount)) in wallets
-        .iter()
-        .filter(|(_, a)| a > &&Amount::ZERO)
-        .enumerate()
-    {
-        let mint_url = mint_url.clone();
-        println!("{i}: {mint_url} {amount} {unit}");
-        wallets_vec.push((mint_url, *amount))
+    let balances = fetch_unit_balances(multi_mint_wallet, None).await?;
+
+    match export {
+        Some(export) => export.finish(&balances.units)?,
+        None => write_csv(&mut io::stdout().lock(), &balances.units)?,
+    }
+
+    Ok(balances.has_balance())
+}
+
+/// CSV export written to a temporary file next to the target path and moved into
+/// place once complete, so a failed export leaves any previous file untouched
+struct CsvExport {
+    path: PathBuf,
+    file: NamedTempFile,
+}
+
+impl CsvExport {
+    fn create(path: &Path) -> Result<Self> {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file = NamedTempFile::new_in(dir)
+            .with_context(|| format!("Cannot write to {}", dir.display()))?;
+
+        // An existing export must itself be writable; opening it for append
+        // checks this without touching its contents
+        if path.exists() {
+            let existing = OpenOptions::new()
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Cannot write to {}", path.display()))?;
+            file.as_file()
+                .set_permissions(existing.metadata()?.permissions())?;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Write the balances and move the export into place
+    fn finish(mut self, all_balances: &[UnitBalances]) -> Result<()> {
+        write_csv(self.file.as_file_mut(), all_balances)?;
+        self.file.as_file().sync_all()?;
+        self.file.persist(&self.path)?;
+        println!("Balances written to {}", self.path.display());
+        Ok(())
+    }
+}
+
+fn write_csv<W>(writer: &mut W, all_balances: &[UnitBalances]) -> Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, "mint_url,amount,unit")?;
+    for unit_balances in all_balances {
+        for (mint_url, amount) in &unit_balances.balances {
+            writeln!(
+                writer,
+                "{},{},{}",
+                csv_field(&mint_url.to_string()),
+                amount,
+                csv_field(&unit_balances.unit.to_string())
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use cdk::nuts::{Id, Proof, PublicKey};
+    use cdk::secret::Secret;
+    use cdk::types::ProofInfo;
+    use cdk_sqlite::wallet::memory;
+
+    use super::*;
+
+    fn mint_url(url: &str) -> MintUrl {
+        MintUrl::from_str(url).unwrap()
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let balances = UnitBalances {
+            unit: CurrencyUnit::Sat,
+            balances: vec![(mint_url("https://mint.example.com"), Amount::from(100))],
+            proof_stats: None,
+        };
+
+        let mut out = Vec::new();
+        write_csv(&mut out, &[balances]).unwrap();
+
+        let csv = String::from_utf8(out).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("mint_url,amount,unit"));
+        assert_eq!(lines.next(), Some("https://mint.example.com,100,sat"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("sat"), "sat");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_proof_stats() {
+        let stats = ProofStats::from_amounts([8, 1, 64, 2, 8].map(Amount::from));
+
+        assert_eq!(stats.proof_count, 5);
+        assert_eq!(stats.min_denom, Some(Amount::from(1)));
+        assert_eq!(stats.max_denom, Some(Amount::from(64)));
+
+        let empty = ProofStats::from_amounts([]);
+        assert_eq!(empty.proof_count, 0);
+        assert_eq!(empty.min_denom, None);
+        assert_eq!(empty.max_denom, None);
+
+        let json = serde_json::to_value(MintBalance {
+            mint_url: mint_url("https://mint.example.com"),
+            amount: Amount::from(83),
+            unit: CurrencyUnit::Sat,
+            proofs: Some(stats),
+        })
+        .unwrap();
+        assert_eq!(json["proof_count"], 5);
+        assert_eq!(json["min_denom"], 1);
+        assert_eq!(json["max_denom"], 64);
+    }
+
+    #[tokio::test]
+    async fn test_verbose_proof_stats_from_wallet() {
+        let (localstore, multi_mint_wallet) = test_wallet(
+            &[
+                ("https://mint1.example.com", CurrencyUnit::Sat, 8),
+                ("https://mint1.example.com", CurrencyUnit::Sat, 1),
+                ("https://mint1.example.com", CurrencyUnit::Sat, 64),
+                ("https://mint2.example.com", CurrencyUnit::Sat, 4),
+                ("https://mint2.example.com", CurrencyUnit::Usd, 32),
+            ],
+            CurrencyUnit::Sat,
+        )
+        .await;
+
+        let stats = proof_stats(&multi_mint_wallet).await.unwrap();
+        assert_eq!(
+            stats.get(&mint_url("https://mint1.example.com")),
+            Some(&ProofStats {
+                proof_count: 3,
+                min_denom: Some(Amount::from(1)),
+                max_denom: Some(Amount::from(64)),
+            })
+        );
+
+        let balances = fetch_unit_balances(&multi_mint_wallet, Some(stats))
+            .await
+            .unwrap();
+        let unit_balances = &balances.units[0];
+        let mut out = Vec::new();
+        write_mint_balances(
+            &mut out,
+            &unit_balances.balances,
+            &unit_balances.unit,
+            unit_balances.proof_stats.as_ref(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "0: https://mint1.example.com 73 sat\n   \
+             3 proofs, smallest 1 sat, largest 64 sat\n\
+             1: https://mint2.example.com 4 sat\n   \
+             1 proof, smallest 4 sat, largest 4 sat\n"
+        );
+
+        // Stats in all-units mode are kept per unit
+        let all_balances = all_unit_balances(&localstore, true).await.unwrap();
+        let usd = all_balances
+            .units
+            .iter()
+            .find(|unit_balances| unit_balances.unit == CurrencyUnit::Usd)
+            .unwrap();
+        assert_eq!(
+            usd.proof_stats
+                .as_ref()
+                .and_then(|stats| stats.get(&mint_url("https://mint2.example.com")))
+                .copied(),
+            Some(ProofStats {
+                proof_count: 1,
+                min_denom: Some(Amount::from(32)),
+                max_denom: Some(Amount::from(32)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_format_unix_time() {
+        assert_eq!(format_unix_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_unix_time(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_unix_time(1_700_000_000), "2023-11-14 22:13:20 UTC");
+        assert_eq!(format_unix_time(4_102_444_799), "2099-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn test_all_units_totals() {
+        assert!(is_all_units(&CurrencyUnit::from_str(ALL_UNITS).unwrap()));
+        assert!(is_all_units(&CurrencyUnit::from_str("ALL").unwrap()));
+        assert!(!is_all_units(&CurrencyUnit::Sat));
+
+        let balances = Balances {
+            units: vec![
+                UnitBalances {
+                    unit: CurrencyUnit::Sat,
+                    balances: vec![
+                        (mint_url("https://mint1.example.com"), Amount::from(100)),
+                        (mint_url("https://mint2.example.com"), Amount::from(50)),
+                    ],
+                    proof_stats: None,
+                },
+                UnitBalances {
+                    unit: CurrencyUnit::Usd,
+                    balances: vec![(mint_url("https://mint1.example.com"), Amount::from(500))],
+                    proof_stats: None,
+                },
+            ],
+            last_updated: 0,
+        };
+
+        assert_eq!(balances.units[0].total().unwrap(), Amount::from(150));
+        assert_eq!(balances.units[1].total().unwrap(), Amount::from(500));
+
+        let json = serde_json::to_value(BalanceOutput::new(&balances).unwrap()).unwrap();
+        assert_eq!(json["units"][0]["unit"], "sat");
+        assert_eq!(json["units"][0]["total"], 150);
+        assert_eq!(json["units"][1]["unit"], "usd");
+        assert_eq!(json["units"][1]["total"], 500);
+        assert_eq!(json["last_updated"], 0);
+
+        let mut out = Vec::new();
+        write_all_units_text(&mut out, &balances).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "sat:\n\
+             0: https://mint1.example.com 100 sat\n\
+             1: https://mint2.example.com 50 sat\n\
+             \n\
+             usd:\n\
+             0: https://mint1.example.com 500 usd\n\
+             \n\
+             Total balance per unit:\n\
+             150 sat\n\
+             500 usd\n\
+             \n\
+             Last updated: 1970-01-01 00:00:00 UTC\n"
+        );
+
+        let empty = Balances {
+            units: vec![],
+            last_updated: 0,
+        };
+        assert!(!empty.has_balance());
+
+        let mut out = Vec::new();
+        write_all_units_text(&mut out, &empty).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "No balance in any unit\n\
+             \n\
+             Last updated: 1970-01-01 00:00:00 UTC\n"
+        );
+
+        let json = serde_json::to_value(BalanceOutput::new(&empty).unwrap()).unwrap();
+        assert_eq!(json["units"], serde_json::json!([]));
+        assert_eq!(json["last_updated"], 0);
+    }
+
+    /// Build a wallet backed by an in-memory database holding unspent proofs of the
+    /// given amounts per mint and unit
+    async fn test_wallet(
+        proofs: &[(&str, CurrencyUnit, u64)],
+        unit: CurrencyUnit,
+    ) -> (
+        Arc<dyn WalletDatabase<Err = cdk_database::Error> + Send + Sync>,
+        MultiMintWallet,
+    ) {
+        let localstore: Arc<dyn WalletDatabase<Err = cdk_database::Error> + Send + Sync> =
+            Arc::new(memory::empty().await.unwrap());
+
+        let keyset_id = Id::from_str("00deadbeef123456").unwrap();
+        let c = PublicKey::from_hex(
+            "02deadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef",
+        )
+        .unwrap();
+
+        let mut added = Vec::new();
+        let mut proof_infos = Vec::new();
+        for (i, (url, proof_unit, amount)) in proofs.iter().enumerate() {
+            let mint_url = mint_url(url);
+            if !added.contains(&mint_url) {
+                localstore.add_mint(mint_url.clone(), None).await.unwrap();
+                added.push(mint_url.clone());
+            }
+
+            let proof = Proof::new(
+                Amount::from(*amount),
+                keyset_id,
+                Secret::new(format!("test_secret_{i}")),
+                c,
+            );
+            proof_infos
+                .push(ProofInfo::new(proof, mint_url, State::Unspent, proof_unit.clone()).unwrap());
+        }
+        localstore.update_proofs(proof_infos, vec![]).await.unwrap();
+
+        let multi_mint_wallet = MultiMintWallet::new(localstore.clone(), [0; 64], unit)
+            .await
+            .unwrap();
+
+        (localstore, multi_mint_wallet)
+    }
+
+    #[tokio::test]
+    async fn test_last_updated_shared_by_text_and_json() {
+        let (_, multi_mint_wallet) = test_wallet(
+            &[
+                ("https://mint.example.com", CurrencyUnit::Sat, 64),
+                ("https://mint.example.com", CurrencyUnit::Sat, 36),
+            ],
+            CurrencyUnit::Sat,
+        )
+        .await;
+
+        let before = unix_time();
+        let balances = fetch_unit_balances(&multi_mint_wallet, None).await.unwrap();
+        let after = unix_time();
+        assert!((before..=after).contains(&balances.last_updated));
+
+        let mut out = Vec::new();
+        write_unit_text(&mut out, &balances).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text,
+            format!(
+                "0: https://mint.example.com 100 sat\n\
+                 \n\
+                 Total balance across all wallets: 100 sat\n\
+                 Last updated: {}\n",
+                format_unix_time(balances.last_updated)
+            )
+        );
+
+        let json = serde_json::to_value(BalanceOutput::new(&balances).unwrap()).unwrap();
+        assert_eq!(json["units"][0]["unit"], "sat");
+        assert_eq!(json["units"][0]["total"], 100);
+        assert_eq!(json["last_updated"], balances.last_updated);
+    }
+
+    #[tokio::test]
+    async fn test_last_updated_printed_when_empty() {
+        let (_, multi_mint_wallet) = test_wallet(&[], CurrencyUnit::Sat).await;
+
+        let balances = fetch_unit_balances(&multi_mint_wallet, None).await.unwrap();
+        assert!(!balances.has_balance());
+
+        let mut out = Vec::new();
+        write_unit_text(&mut out, &balances).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "Last updated: {}\n",
+                format_unix_time(balances.last_updated)
+            )
+        );
+    }
+
+    fn balance_args(fail_if_empty: bool) -> BalanceSubCommand {
+        BalanceSubCommand {
+            csv: None,
+            json: false,
+            verbose: false,
+            fail_if_empty,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_has_balance_empty_wallet() {
+        let (localstore, multi_mint_wallet) = test_wallet(&[], CurrencyUnit::Sat).await;
+
+        assert!(!unit_balance(&multi_mint_wallet, &balance_args(false))
+            .await
+            .unwrap());
+        assert!(!all_units_balance(&localstore, &balance_args(false))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_has_balance_non_empty_wallet() {
+        let (localstore, multi_mint_wallet) = test_wallet(
+            &[("https://mint.example.com", CurrencyUnit::Sat, 8)],
+            CurrencyUnit::Sat,
+        )
+        .await;
+
+        assert!(unit_balance(&multi_mint_wallet, &balance_args(false))
+            .await
+            .unwrap());
+        assert!(all_units_balance(&localstore, &balance_args(false))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_has_balance_only_in_other_unit() {
+        let (localstore, multi_mint_wallet) = test_wallet(
+            &[("https://mint.example.com", CurrencyUnit::Usd, 8)],
+            CurrencyUnit::Sat,
+        )
+        .await;
+
+        // The sat wallet is empty, but `--unit all` still sees the usd balance
+        assert!(!unit_balance(&multi_mint_wallet, &balance_args(false))
+            .await
+            .unwrap());
+        assert!(all_units_balance(&localstore, &balance_args(false))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_fail_if_empty_exit_code() {
+        let (localstore, empty_wallet) = test_wallet(&[], CurrencyUnit::Sat).await;
+        let has_balance = balance(&empty_wallet, &localstore, &balance_args(true))
+            .await
+            .unwrap();
+        assert_eq!(exit_code(&balance_args(true), has_balance), 2);
+        assert_eq!(exit_code(&balance_args(false), has_balance), 0);
+
+        let (localstore, wallet) = test_wallet(
+            &[("https://mint.example.com", CurrencyUnit::Sat, 8)],
+            CurrencyUnit::Sat,
+        )
+        .await;
+        let has_balance = balance(&wallet, &localstore, &balance_args(true))
+            .await
+            .unwrap();
+        assert_eq!(exit_code(&balance_args(true), has_balance), 0);
+        assert_eq!(exit_code(&balance_args(false), has_balance), 0);
+    }
+
+    #[test]
+    fn test_csv_export_keeps_existing_file_until_finished() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("balances.csv");
+        let sibling = dir.path().join("balances.csv.tmp");
+        fs::write(&path, "previous export").unwrap();
+        fs::write(&sibling, "unrelated file").unwrap();
+
+        // An export that is dropped without finishing leaves the old file intact
+        drop(CsvExport::create(&path).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous export");
+
+        let export = CsvExport::create(&path).unwrap();
+        export
+            .finish(&[UnitBalances {
+                unit: CurrencyUnit::Sat,
+                balances: vec![(mint_url("https://mint.example.com"), Amount::from(100))],
+                proof_stats: None,
+            }])
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "mint_url,amount,unit\nhttps://mint.example.com,100,sat\n"
+        );
+
+        // Only the export and the user's own files remain in the directory
+        assert_eq!(fs::read_to_string(&sibling).unwrap(), "unrelated file");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_csv_export_missing_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("balances.csv");
+
+        assert!(CsvExport::create(&path).is_err());
     }
-    Ok(wallets_vec)
 }